@@ -1,3 +1,4 @@
+use std::collections::HashSet;
 use std::sync::{Arc, Mutex};
 
 use tokio::io::{stdout, AsyncWriteExt};
@@ -5,15 +6,49 @@ use tokio_stream::StreamExt as _;
 
 use crate::{
     generation::{
-        chat::{request::ChatMessageRequest, ChatMessage, ChatMessageResponse, MessageRole},
+        chat::{
+            request::{ChatMessageRequest, ToolChoice},
+            ChatMessage, ChatMessageResponse, MessageRole,
+        },
+        functions::pipelines::nous_hermes::prompts::DEFAULT_SYSTEM_TEMPLATE,
         parameters::FormatType,
-        tools::ToolGroup,
+        tools::{ToolCallFunction, ToolGroup},
     },
     history::ChatHistory,
     models::ModelOptions,
+    rate_limiter::RateLimiter,
     Ollama,
 };
 
+/// Default cap on both the `chat_react` loop and the native tool-calling
+/// recursion in `chat`/`chat_iteration`, so neither runs forever if the
+/// model never stops requesting tool calls.
+const DEFAULT_MAX_ITERATIONS: u32 = 10;
+
+/// Default context window size set on a freshly created `Coordinator`.
+const DEFAULT_NUM_CTX: u32 = 4096;
+
+/// Token usage recorded by a `Coordinator`, summed across its `chat` calls
+/// and any tool-calling recursion steps they took.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct UsageMetadata {
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+    pub total_tokens: u32,
+}
+
+impl std::ops::Add for UsageMetadata {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self {
+            input_tokens: self.input_tokens + rhs.input_tokens,
+            output_tokens: self.output_tokens + rhs.output_tokens,
+            total_tokens: self.total_tokens + rhs.total_tokens,
+        }
+    }
+}
+
 /// A coordinator for managing chat interactions and tool usage.
 ///
 /// This struct is responsible for coordinating chat messages and tool
@@ -27,6 +62,11 @@ pub struct Coordinator<C: ChatHistory, T: ToolGroup> {
     tools: T,
     debug: bool,
     format: Option<FormatType>,
+    sink: Option<Box<dyn FnMut(&str) + Send>>,
+    usage: UsageMetadata,
+    tool_choice: Option<ToolChoice>,
+    rate_limiter: Option<RateLimiter>,
+    max_iterations: u32,
 }
 
 impl<C: ChatHistory> Coordinator<C, ()> {
@@ -45,11 +85,16 @@ impl<C: ChatHistory> Coordinator<C, ()> {
         Self {
             model,
             ollama,
-            options: ModelOptions::default(),
+            options: ModelOptions::default().num_ctx(DEFAULT_NUM_CTX),
             history: Arc::new(Mutex::new(history)),
             tools: (),
             debug: false,
             format: None,
+            sink: None,
+            usage: UsageMetadata::default(),
+            tool_choice: None,
+            rate_limiter: None,
+            max_iterations: DEFAULT_MAX_ITERATIONS,
         }
     }
 }
@@ -71,11 +116,16 @@ impl<C: ChatHistory, T: ToolGroup> Coordinator<C, T> {
         Self {
             model,
             ollama,
-            options: ModelOptions::default(),
+            options: ModelOptions::default().num_ctx(DEFAULT_NUM_CTX),
             history: Arc::new(Mutex::new(history)),
             tools,
             debug: false,
             format: None,
+            sink: None,
+            usage: UsageMetadata::default(),
+            tool_choice: None,
+            rate_limiter: None,
+            max_iterations: DEFAULT_MAX_ITERATIONS,
         }
     }
 
@@ -84,25 +134,142 @@ impl<C: ChatHistory, T: ToolGroup> Coordinator<C, T> {
         self
     }
 
+    /// Registers a callback invoked with each token as it streams in, instead
+    /// of the default behavior of writing it to stdout. Useful for routing
+    /// partial tokens elsewhere, e.g. editing a message in place as it's
+    /// generated.
+    pub fn on_token(mut self, sink: impl FnMut(&str) + Send + 'static) -> Self {
+        self.sink = Some(Box::new(sink));
+        self
+    }
+
     pub fn options(mut self, options: ModelOptions) -> Self {
         self.options = options;
         self
     }
 
+    /// Sets the context window size (`num_ctx`) used for generation.
+    /// Defaults to 4096.
+    pub fn num_ctx(mut self, num_ctx: u32) -> Self {
+        self.options = self.options.num_ctx(num_ctx);
+        self
+    }
+
     pub fn debug(mut self, debug: bool) -> Self {
         self.debug = debug;
         self
     }
 
+    /// Forces or disables tool calling for subsequent turns, e.g. requiring
+    /// a specific tool with `ToolChoice::Specific("hello_world".to_string())`.
+    /// Defaults to `ToolChoice::Auto`, letting the model decide.
+    pub fn tool_choice(mut self, tool_choice: ToolChoice) -> Self {
+        self.tool_choice = Some(tool_choice);
+        self
+    }
+
+    /// Caps outbound chat requests to `max_requests_per_second`, sleeping
+    /// until a permit is available. This creates a limiter private to this
+    /// coordinator; use `rate_limiter` instead to share a single cap across
+    /// multiple coordinators built from the same `Ollama` client.
+    pub fn rate_limit(mut self, max_requests_per_second: f32) -> Self {
+        self.rate_limiter = Some(RateLimiter::new(max_requests_per_second));
+        self
+    }
+
+    /// Shares an existing `RateLimiter` instead of creating a new,
+    /// independent bucket. Clone the same `RateLimiter` into every
+    /// coordinator that talks to a given `Ollama` client to enforce one
+    /// global requests-per-second cap across all of them.
+    pub fn rate_limiter(mut self, rate_limiter: RateLimiter) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    /// Sets the iteration cap shared by `chat_react` and the native
+    /// tool-calling recursion in `chat`/`chat_iteration`. Defaults to 10.
+    pub fn max_iterations(mut self, max_iterations: u32) -> Self {
+        self.max_iterations = max_iterations;
+        self
+    }
+
     /// Get a handle to the history
     pub fn history(&self) -> Arc<Mutex<C>> {
         self.history.clone()
     }
 
+    /// Verifies that `self.model` has been pulled and is available on the
+    /// configured Ollama server, returning a clear error up front if the
+    /// server isn't running or the model is missing, rather than failing
+    /// mid-stream on first use.
+    pub async fn ensure_model_available(&self) -> crate::error::Result<()> {
+        let models = self.ollama.list_local_models().await?;
+
+        if models.iter().any(|model| model.name == self.model) {
+            Ok(())
+        } else {
+            Err(crate::error::OllamaError::Other(format!(
+                "model '{}' is not available on the configured Ollama server; pull it first with `ollama pull {}`",
+                self.model, self.model
+            )))
+        }
+    }
+
+    /// Requests a response that deserializes into `R`, using its derived
+    /// JSON schema as the structured-output format. This is a one-shot,
+    /// no-tool-calling turn, so it also forces `ToolChoice::None` for the
+    /// duration of the call — otherwise the tool/format ordering workaround
+    /// in `chat` would withhold the schema until a tool has already run,
+    /// and a turn with tools registered but none invoked would never get a
+    /// schema-constrained response at all.
+    pub async fn chat_structured<R>(&mut self, messages: Vec<ChatMessage>) -> crate::error::Result<R>
+    where
+        R: serde::de::DeserializeOwned + schemars::JsonSchema,
+    {
+        let schema = schemars::schema_for!(R);
+        let previous_format = self.format.take();
+        let previous_tool_choice = self.tool_choice.take();
+
+        self.format = Some(FormatType::StructuredJson(serde_json::to_value(
+            &schema,
+        )?));
+        self.tool_choice = Some(ToolChoice::None);
+
+        let resp = self.chat(messages).await;
+        self.format = previous_format;
+        self.tool_choice = previous_tool_choice;
+
+        Ok(serde_json::from_str(&resp?.message.content)?)
+    }
+
+    /// Returns the cumulative token usage recorded across every `chat` call
+    /// made by this coordinator, including any tool-calling recursion steps.
+    pub fn usage(&self) -> UsageMetadata {
+        self.usage
+    }
+
     pub async fn chat(
         &mut self,
         messages: Vec<ChatMessage>,
     ) -> crate::error::Result<ChatMessageResponse> {
+        self.chat_iteration(messages, 0).await
+    }
+
+    /// Implements `chat`, threading an iteration count through the
+    /// tool-calling recursion so it's bounded by `max_iterations` instead of
+    /// recursing forever if the model keeps returning `tool_calls`.
+    async fn chat_iteration(
+        &mut self,
+        messages: Vec<ChatMessage>,
+        iteration: u32,
+    ) -> crate::error::Result<ChatMessageResponse> {
+        if iteration >= self.max_iterations {
+            return Err(crate::error::OllamaError::Other(format!(
+                "exceeded max_iterations ({}) while recursing through tool calls",
+                self.max_iterations
+            )));
+        }
+
         if self.debug {
             for m in &messages {
                 eprintln!("Hit {} with:", self.model);
@@ -114,15 +281,25 @@ impl<C: ChatHistory, T: ToolGroup> Coordinator<C, T> {
             .options(self.options.clone())
             .tools::<T>();
 
+        if let Some(tool_choice) = &self.tool_choice {
+            request = request.tool_choice(tool_choice.clone());
+        }
+
         if let Some(format) = &self.format {
             let mut tools = vec![];
             T::tool_info(&mut tools);
 
+            // Tool calling doesn't apply to this turn either if there's nothing to call,
+            // or if it's been explicitly disabled via `ToolChoice::None` (as `chat_structured`
+            // does) — in both cases there's no recursive tool-result turn to wait for.
+            let no_tool_calls_expected =
+                tools.is_empty() || matches!(self.tool_choice, Some(ToolChoice::None));
+
             // If no tools are specified, set the format on the request. Otherwise wait for the
             // recursive call by checking that the last message in the history has a Tool role,
             // before setting the format. Ollama otherwise won't call the tool if the format
             // is set on the first request.
-            if tools.is_empty() {
+            if no_tool_calls_expected {
                 request = request.format(format.clone());
             } else if let Some(last_message) = self
                 .history
@@ -137,36 +314,56 @@ impl<C: ChatHistory, T: ToolGroup> Coordinator<C, T> {
             }
         }
 
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter.acquire().await;
+        }
+
         let mut stream = self
             .ollama
             .send_chat_messages_with_history_stream(self.history.clone(), request)
             .await?;
 
         let mut response = String::new();
+        let mut tool_calls = vec![];
+        let mut seen_tool_calls = HashSet::new();
+        let mut final_data = None;
         while let Some(Ok(res)) = stream.next().await {
-            stdout().write_all(res.message.content.as_bytes()).await?;
-            stdout().flush().await?;
+            if let Some(sink) = self.sink.as_mut() {
+                sink(&res.message.content);
+            } else {
+                stdout().write_all(res.message.content.as_bytes()).await?;
+                stdout().flush().await?;
+            }
             response += res.message.content.as_str();
-        }
 
-        Ok(ChatMessageResponse {
-            model: self.model.clone(),
-            created_at: String::default(),
-            message: ChatMessage::assistant(response),
-            done: true,
-            final_data: None,
-        })
+            // Tool calls can arrive spread across multiple chunks even while
+            // streaming, and Ollama may repeat an already-resolved call
+            // verbatim in later chunks before `done`, so dedupe by the
+            // serialized function call before queuing it for dispatch —
+            // otherwise a repeated chunk would invoke the same tool twice.
+            for call in &res.message.tool_calls {
+                if seen_tool_calls.insert(serde_json::to_string(&call.function)?) {
+                    tool_calls.push(call.clone());
+                }
+            }
 
-        /*
-        let resp = self
-            .ollama
-            .send_chat_messages_with_history(&mut self.history, request)
-            .await?;
-        */
+            if res.final_data.is_some() {
+                final_data = res.final_data;
+            }
+        }
+
+        self.usage = self.usage
+            + final_data
+                .as_ref()
+                .map(|data| UsageMetadata {
+                    input_tokens: data.prompt_eval_count as u32,
+                    output_tokens: data.eval_count as u32,
+                    total_tokens: data.prompt_eval_count as u32 + data.eval_count as u32,
+                })
+                .unwrap_or_default();
 
-        /*
-        if !resp.message.tool_calls.is_empty() {
-            for call in resp.message.tool_calls {
+        if !tool_calls.is_empty() {
+            for call in tool_calls {
                 if self.debug {
                     eprintln!("Tool call: {:?}", call.function);
                 }
@@ -177,12 +374,23 @@ impl<C: ChatHistory, T: ToolGroup> Coordinator<C, T> {
                     eprintln!("Tool response: {}", &resp);
                 }
 
-                self.history.push(ChatMessage::tool(resp))
+                self.history
+                    .lock()
+                    .map_err(|_| crate::error::OllamaError::MutexPoisoned)?
+                    .push(ChatMessage::tool(resp));
             }
 
             // recurse
-            Box::pin(self.chat(vec![])).await
+            Box::pin(self.chat_iteration(vec![], iteration + 1)).await
         } else {
+            let resp = ChatMessageResponse {
+                model: self.model.clone(),
+                created_at: String::default(),
+                message: ChatMessage::assistant(response),
+                done: true,
+                final_data,
+            };
+
             if self.debug {
                 eprintln!(
                     "Response from {} of type {:?}: '{}'",
@@ -192,6 +400,71 @@ impl<C: ChatHistory, T: ToolGroup> Coordinator<C, T> {
 
             Ok(resp)
         }
-        */
     }
+
+    /// Runs a ReAct-style loop: the model is prompted with a system template
+    /// describing the available tools and expected `<tool_call>` XML shape,
+    /// and is allowed up to `max_iterations` rounds of reasoning and tool use
+    /// before the loop gives up and returns whatever summary it accumulated.
+    pub async fn chat_react(&mut self, messages: Vec<ChatMessage>) -> crate::error::Result<String> {
+        let mut tools = vec![];
+        T::tool_info(&mut tools);
+
+        let system_prompt = DEFAULT_SYSTEM_TEMPLATE
+            .replace(
+                "{tools}",
+                &tools
+                    .iter()
+                    .map(|t| format!("{:?}", t))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            )
+            .replace("{schema}", "<tool_call>{\"name\": <function-name>, \"arguments\": <args-dict>}</tool_call>")
+            .replace("{date}", &chrono::Local::now().format("%Y-%m-%d").to_string());
+
+        self.history
+            .lock()
+            .map_err(|_| crate::error::OllamaError::MutexPoisoned)?
+            .push(ChatMessage::system(system_prompt));
+
+        let mut next_messages = messages;
+        let mut summary = String::new();
+
+        for _ in 0..self.max_iterations {
+            let resp = self.chat(next_messages).await?;
+            next_messages = vec![];
+
+            match parse_xml_tool_call(&resp.message.content) {
+                Some(call) => {
+                    let tool_resp = self.tools.call(&call).await?;
+                    summary.push_str(&tool_resp);
+                    summary.push('\n');
+
+                    self.history
+                        .lock()
+                        .map_err(|_| crate::error::OllamaError::MutexPoisoned)?
+                        .push(ChatMessage::tool(format!(
+                            "<tool_response>{}</tool_response>",
+                            tool_resp
+                        )));
+                }
+                None => {
+                    summary.push_str(&resp.message.content);
+                    return Ok(summary);
+                }
+            }
+        }
+
+        Ok(summary)
+    }
+}
+
+/// Parses a `<tool_call>{...}</tool_call>` block out of a ReAct-style
+/// response, returning `None` if the model didn't request a tool call.
+fn parse_xml_tool_call(content: &str) -> Option<ToolCallFunction> {
+    let start = content.find("<tool_call>")? + "<tool_call>".len();
+    let end = content.find("</tool_call>")?;
+    let json = content.get(start..end)?.trim();
+
+    serde_json::from_str(json).ok()
 }
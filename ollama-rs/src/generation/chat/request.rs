@@ -0,0 +1,100 @@
+use serde::{Serialize, Serializer};
+
+use crate::generation::{chat::ChatMessage, parameters::FormatType, tools::ToolInfo};
+use crate::models::ModelOptions;
+
+/// Controls whether, and which, tool the model is allowed to call on a turn.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ToolChoice {
+    /// Let the model decide whether to call a tool. This is the default.
+    Auto,
+    /// Disable tool calling for this turn.
+    None,
+    /// Require the model to call some tool from the group.
+    Required,
+    /// Require the model to call the named tool.
+    Specific(String),
+}
+
+impl Serialize for ToolChoice {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        #[derive(Serialize)]
+        struct FunctionName<'a> {
+            name: &'a str,
+        }
+
+        #[derive(Serialize)]
+        struct NamedTool<'a> {
+            #[serde(rename = "type")]
+            kind: &'a str,
+            function: FunctionName<'a>,
+        }
+
+        match self {
+            ToolChoice::Auto => serializer.serialize_str("auto"),
+            ToolChoice::None => serializer.serialize_str("none"),
+            ToolChoice::Required => serializer.serialize_str("required"),
+            ToolChoice::Specific(name) => NamedTool {
+                kind: "function",
+                function: FunctionName { name },
+            }
+            .serialize(serializer),
+        }
+    }
+}
+
+/// A request to the `/api/chat` endpoint.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChatMessageRequest {
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<FormatType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<ModelOptions>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<ToolInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<ToolChoice>,
+}
+
+impl ChatMessageRequest {
+    pub fn new(model: String, messages: Vec<ChatMessage>) -> Self {
+        Self {
+            model,
+            messages,
+            format: None,
+            options: None,
+            tools: vec![],
+            tool_choice: None,
+        }
+    }
+
+    pub fn format(mut self, format: FormatType) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    pub fn options(mut self, options: ModelOptions) -> Self {
+        self.options = Some(options);
+        self
+    }
+
+    /// Advertises every tool in `T` to the model.
+    pub fn tools<T: crate::generation::tools::ToolGroup>(mut self) -> Self {
+        let mut tools = vec![];
+        T::tool_info(&mut tools);
+        self.tools = tools;
+        self
+    }
+
+    /// Forces or disables tool calling for this request, serialized as
+    /// Ollama's `tool_choice` field.
+    pub fn tool_choice(mut self, tool_choice: ToolChoice) -> Self {
+        self.tool_choice = Some(tool_choice);
+        self
+    }
+}
@@ -0,0 +1,115 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use tokio::sync::Mutex;
+use tokio::time::Duration;
+
+/// A token-bucket rate limiter shared behind an `Arc`. Clone it and hand the
+/// same `RateLimiter` to multiple `Coordinator`s (e.g. via
+/// `Coordinator::rate_limiter`) so they respect a single global
+/// requests-per-second cap instead of each getting an independent bucket.
+#[derive(Clone)]
+pub struct RateLimiter {
+    inner: Arc<Mutex<Bucket>>,
+}
+
+struct Bucket {
+    tokens: f32,
+    max_tokens: f32,
+    refill_per_sec: f32,
+    last_refill: Instant,
+}
+
+/// Floor applied to `max_requests_per_second` so the refill rate can never be
+/// non-positive or so small that `acquire` computes a wait duration too large
+/// for `Duration::from_secs_f32` (which panics on non-finite input).
+const MIN_REQUESTS_PER_SECOND: f32 = 1e-3;
+
+impl RateLimiter {
+    /// Creates a limiter that allows at most `max_requests_per_second`
+    /// outbound requests per second, refilled continuously based on elapsed
+    /// time. Values below `MIN_REQUESTS_PER_SECOND` (including zero and
+    /// negative input) are clamped up to it, since a near-zero refill rate
+    /// would otherwise make `acquire` compute a wait duration that overflows
+    /// `Duration::from_secs_f32` and panics. The bucket always holds at least
+    /// one permit, so rates below one request per second still let a request
+    /// through once a full permit has refilled.
+    pub fn new(max_requests_per_second: f32) -> Self {
+        let max_requests_per_second = max_requests_per_second.max(MIN_REQUESTS_PER_SECOND);
+        let max_tokens = max_requests_per_second.max(1.0);
+
+        Self {
+            inner: Arc::new(Mutex::new(Bucket {
+                tokens: max_tokens,
+                max_tokens,
+                refill_per_sec: max_requests_per_second,
+                last_refill: Instant::now(),
+            })),
+        }
+    }
+
+    /// Blocks until a permit is available, sleeping for the time it takes to
+    /// refill one permit whenever the bucket holds less than one.
+    pub(crate) async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut bucket = self.inner.lock().await;
+
+                let elapsed = bucket.last_refill.elapsed().as_secs_f32();
+                bucket.tokens =
+                    (bucket.tokens + elapsed * bucket.refill_per_sec).min(bucket.max_tokens);
+                bucket.last_refill = Instant::now();
+
+                if bucket.tokens >= 1.0 {
+                    bucket.tokens -= 1.0;
+                    None
+                } else {
+                    let missing = 1.0 - bucket.tokens;
+                    Some(Duration::from_secs_f32(missing / bucket.refill_per_sec))
+                }
+            };
+
+            match wait {
+                Some(wait) => tokio::time::sleep(wait).await,
+                None => return,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn non_positive_rate_does_not_panic() {
+        RateLimiter::new(0.0).acquire().await;
+        RateLimiter::new(-5.0).acquire().await;
+    }
+
+    #[tokio::test]
+    async fn permits_up_to_the_burst_size_without_waiting() {
+        let limiter = RateLimiter::new(2.0);
+
+        let start = Instant::now();
+        limiter.acquire().await;
+        limiter.acquire().await;
+
+        assert!(start.elapsed() < Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn clone_shares_the_same_bucket() {
+        let limiter = RateLimiter::new(1.0);
+        let shared = limiter.clone();
+
+        limiter.acquire().await;
+
+        // The second acquire goes through the clone's bucket, which should
+        // already be drained by the first acquire on `limiter`.
+        let start = Instant::now();
+        shared.acquire().await;
+
+        assert!(start.elapsed() >= Duration::from_millis(400));
+    }
+}